@@ -1,7 +1,8 @@
 use std::convert::TryInto;
 
 use bytes::{ Bytes, BytesMut, BufMut };
-use tokio_util::codec::{ Decoder, Encoder };
+use rand::Rng;
+use tokio_util::codec::Decoder;
 
 use crate::{
     Message,
@@ -11,10 +12,105 @@ use crate::{
     FrameBuilder,
     Opcode,
 };
+use crate::frame::{ FrameData, Role };
+use crate::permessage_deflate::{ PerMessageDeflate, PerMessageDeflateConfig };
+
+// Above this, an unmasked (server-side) payload is written to the socket by reference
+// (`FrameData::Split`) instead of being copied alongside its header into one buffer.
+const SPLIT_THRESHOLD: usize = 4096;
 
 #[derive(Debug, Default)]
 pub struct WebsocketCodec {
     frame_builder: FrameBuilder,
+    role: Role,
+    deflate: Option<PerMessageDeflate>,
+    fragment_size: Option<usize>,
+}
+
+impl WebsocketCodec {
+    /// Build a codec acting as the WebSocket client: outgoing frames are masked with a
+    /// fresh random key and incoming (server) frames must be unmasked.
+    pub fn client() -> Self {
+        Self::new(Role::Client)
+    }
+
+    /// Build a codec acting as the WebSocket server: outgoing frames are sent unmasked
+    /// and incoming (client) frames must be masked.
+    pub fn server() -> Self {
+        Self::new(Role::Server)
+    }
+
+    fn new(role: Role) -> Self {
+        let mut frame_builder = FrameBuilder::default();
+        frame_builder.role = role;
+
+        Self { frame_builder, role, deflate: None, fragment_size: None }
+    }
+
+    /// Split outgoing text/binary messages whose payload (after compression, when
+    /// `permessage-deflate` is on) is larger than `size` into a leading frame plus
+    /// `Opcode::Continuation` frames, instead of sending the whole payload in one frame.
+    /// Control frames (Close/Ping/Pong) are never fragmented. `size == 0` is treated as
+    /// "no fragmentation" (same as never calling this) rather than splitting into an
+    /// unbounded number of empty chunks.
+    pub fn fragment_size(mut self, size: usize) -> Self {
+        self.fragment_size = if size == 0 { None } else { Some(size) };
+        self
+    }
+
+    /// Cap the payload of a single frame. Frames exceeding this are rejected with
+    /// close code 1009 (Message Too Big) instead of being allocated.
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.frame_builder.max_frame_size = size;
+        self
+    }
+
+    /// Cap the total payload of a (possibly fragmented) message. Exceeding this across
+    /// continuation frames is rejected with close code 1009 (Message Too Big).
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.frame_builder.max_message_size = size;
+        self
+    }
+
+    /// Negotiate the `permessage-deflate` extension (RFC 7692): incoming messages with
+    /// RSV1 set are inflated and outgoing text/binary messages are deflated.
+    pub fn permessage_deflate(mut self, config: PerMessageDeflateConfig) -> Self {
+        self.frame_builder.compression_enabled = true;
+        self.deflate = Some(PerMessageDeflate::new(config, self.role));
+        self
+    }
+
+    /// Concatenate a complete message's frames and, if RSV1 was set on the first one,
+    /// inflate them back into application bytes.
+    ///
+    /// Text messages are validated as UTF-8 here only when compressed: the uncompressed
+    /// case is already validated incrementally, frame by frame, by `FrameBuilder` as the
+    /// bytes arrive on the wire, but a compressed frame's raw bytes aren't text until
+    /// after this decompresses them.
+    pub(crate) fn assemble_message_payload(&mut self, opcode: Opcode, frames: &[Frame]) -> WebsocketResult<Bytes> {
+        let mut raw = BytesMut::new();
+
+        for frame in frames {
+            raw.put_slice(&frame.buf[..]);
+        }
+
+        let compressed = frames.first().is_some_and(|frame| frame.rsv & 0b100 != 0);
+
+        let payload = if !compressed {
+            raw.freeze()
+        } else {
+            match self.deflate.as_mut() {
+                Some(deflate) => deflate.decompress(&raw, self.frame_builder.max_message_size)?,
+                None => raw.freeze(),
+            }
+        };
+
+        if compressed && opcode.is_text() {
+            String::from_utf8(payload.to_vec())?;
+        }
+
+        Ok(payload)
+    }
 }
 
 impl Decoder for WebsocketCodec {
@@ -35,8 +131,13 @@ impl Decoder for WebsocketCodec {
                 || (frame_index > 0 && matches!(frame.opcode, Opcode::Ping))
             {
                 self.frame_builder.frame_index = frame_index + 1;
+
+                if matches!(frame.opcode, Opcode::Text | Opcode::Binary | Opcode::Continuation) {
+                    self.frame_builder.message_len += frame.buf_len;
+                }
             } else if frame.fin == true && matches!(frame.opcode, Opcode::Continuation) {
                 self.frame_builder.frame_index = 0;
+                self.frame_builder.message_len = 0;
             }
 
             return Ok(Some(frame))
@@ -46,28 +147,211 @@ impl Decoder for WebsocketCodec {
     }
 }
 
-impl Encoder<Message> for WebsocketCodec {
-    type Error = WebsocketError;
+impl WebsocketCodec {
+    /// Split `payload` into the chunks that each outgoing frame of the message will
+    /// carry: a single chunk unless fragmentation is configured, non-control, and the
+    /// payload exceeds `fragment_size`. Each chunk is a cheap `Bytes` slice sharing the
+    /// same underlying buffer as `payload`, not a copy.
+    fn _chunks(&self, payload: &Bytes, is_control: bool) -> Vec<Bytes> {
+        match self.fragment_size {
+            Some(size) if !is_control && payload.len() > size => {
+                let mut chunks = Vec::new();
+                let mut start = 0;
+
+                while start < payload.len() {
+                    let end = (start + size).min(payload.len());
+                    chunks.push(payload.slice(start..end));
+                    start = end;
+                }
+
+                chunks
+            },
+            _ => vec![payload.clone()],
+        }
+    }
+
+    /// Turn a `Message` into the `FrameData`s that must be written to the wire, in
+    /// order: one frame unless it had to be fragmented.
+    pub(crate) fn encode_message(&mut self, msg: Message) -> WebsocketResult<Vec<FrameData>> {
+        let is_control = msg.is_close || msg.is_pong;
+        let compress = self.deflate.is_some() && (msg.is_text || msg.is_binary);
 
-    fn encode(&mut self, msg: Message, buf: &mut BytesMut) -> WebsocketResult<()> {
-        if msg.is_close { buf.put_u8(0x88); /* 1000 1000 */ }
-        else if msg.is_text { buf.put_u8(0x81); /* 1000 0001 */ }
-        else if msg.is_binary { buf.put_u8(0x82); /* 1000 0010 */ }
-        else if msg.is_pong { buf.put_u8(0x8A); /* 1000 1010 */ }
-        else { unimplemented!() };
-
-        if msg.buf.len() > u16::MAX as usize {
-            buf.put_u8(0x7F); // 0111 1111
-            buf.put_u64(msg.buf.len() as u64);
-        } else if msg.buf.len() > 125 {
-            buf.put_u8(0x7E); // 0111 1110
-            buf.put_u16(msg.buf.len() as u16);
+        let payload = if compress {
+            self.deflate.as_mut().unwrap().compress(&msg.buf)?
         } else {
-            buf.put_u8(msg.buf.len() as u8);
+            msg.buf
+        };
+
+        let opcode_bits = if msg.is_close { 0x08 }
+            else if msg.is_text { 0x01 }
+            else if msg.is_binary { 0x02 }
+            else if msg.is_pong { 0x0A }
+            else { unimplemented!() };
+
+        let chunks = self._chunks(&payload, is_control);
+        let is_client = self.role == Role::Client;
+        let last = chunks.len() - 1;
+
+        let frames = chunks.into_iter().enumerate().map(|(i, chunk)| {
+            let fin_bit = if i == last { 0x80 } else { 0x00 };
+            let rsv1_bit = if compress && i == 0 { 0x40 } else { 0x00 };
+            let opcode_bits = if i == 0 { opcode_bits } else { 0x00 /* Continuation */ };
+
+            Self::_frame_data(fin_bit | rsv1_bit | opcode_bits, chunk, is_client)
+        }).collect();
+
+        Ok(frames)
+    }
+
+    /// Build the header (first byte, length, masking key) for one frame and pair it
+    /// with its payload, copying the payload only when it must be masked or is small
+    /// enough that a second write isn't worth avoiding.
+    fn _frame_data(first_byte: u8, payload: Bytes, is_client: bool) -> FrameData {
+        let mask_bit = if is_client { 0x80 } else { 0x00 };
+
+        let mut header = BytesMut::with_capacity(10);
+        header.put_u8(first_byte);
+
+        if payload.len() > u16::MAX as usize {
+            header.put_u8(0x7F | mask_bit); // 0111 1111
+            header.put_u64(payload.len() as u64);
+        } else if payload.len() > 125 {
+            header.put_u8(0x7E | mask_bit); // 0111 1110
+            header.put_u16(payload.len() as u16);
+        } else {
+            header.put_u8(payload.len() as u8 | mask_bit);
+        }
+
+        if is_client {
+            // Masking always copies the payload to XOR it, so there's nothing to gain
+            // by keeping it separate from the header.
+            let masking_key: [u8; 4] = rand::thread_rng().gen();
+            header.put_slice(&masking_key);
+
+            let mut masked = payload.to_vec();
+            for (i, byte) in masked.iter_mut().enumerate() {
+                *byte ^= masking_key[i % 4];
+            }
+            header.put_slice(&masked);
+
+            return FrameData::Complete(header.freeze())
+        }
+
+        if payload.len() <= SPLIT_THRESHOLD {
+            header.put_slice(&payload);
+            FrameData::Complete(header.freeze())
+        } else {
+            FrameData::Split(header.freeze(), payload)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &Bytes, role: Role) -> Frame {
+        let mut builder = FrameBuilder::default();
+        builder.role = role;
+
+        let mut buf = BytesMut::from(&bytes[..]);
+        builder.build(&mut buf).unwrap()
+    }
+
+    fn complete_bytes(frame: FrameData) -> Bytes {
+        match frame {
+            FrameData::Complete(bytes) => bytes,
+            FrameData::Split(..) => panic!("expected a complete frame"),
         }
+    }
+
+    #[test]
+    fn client_frames_are_masked_and_decode_back_to_the_original_payload() {
+        let mut codec = WebsocketCodec::client();
+        let mut frames = codec.encode_message(Message::from_text("hi".to_string())).unwrap();
+        assert_eq!(frames.len(), 1);
+
+        let bytes = complete_bytes(frames.remove(0));
+        assert_eq!(bytes[1] & 0x80, 0x80, "mask bit must be set on the wire");
+
+        let frame = decode(&bytes, Role::Server);
+        assert_eq!(frame.opcode, Opcode::Text);
+        assert_eq!(&frame.buf[..], b"hi");
+    }
+
+    #[test]
+    fn server_frames_are_unmasked_and_rejected_by_a_server_decoder() {
+        let mut codec = WebsocketCodec::server();
+        let mut frames = codec.encode_message(Message::from_text("hi".to_string())).unwrap();
+
+        let bytes = complete_bytes(frames.remove(0));
+        assert_eq!(bytes[1] & 0x80, 0x00, "server output must not be masked");
+
+        // A server only ever receives masked client frames (RFC 6455 5.1), so its own
+        // unmasked output would be a protocol violation if fed back to a server decoder.
+        let frame = decode(&bytes, Role::Server);
+        assert_eq!(frame.opcode, Opcode::Close);
+
+        // A client decoder, on the other hand, accepts it.
+        let frame = decode(&bytes, Role::Client);
+        assert_eq!(frame.opcode, Opcode::Text);
+        assert_eq!(&frame.buf[..], b"hi");
+    }
+
+    #[test]
+    fn chunks_splits_on_the_boundary() {
+        let codec = WebsocketCodec::server().fragment_size(4);
+        let payload = Bytes::from_static(b"0123456789");
+
+        let chunks = codec._chunks(&payload, false);
+
+        assert_eq!(chunks, vec![
+            Bytes::from_static(b"0123"),
+            Bytes::from_static(b"4567"),
+            Bytes::from_static(b"89"),
+        ]);
+    }
+
+    #[test]
+    fn chunks_leaves_small_payloads_whole() {
+        let codec = WebsocketCodec::server().fragment_size(1024);
+        let payload = Bytes::from_static(b"hello");
+
+        assert_eq!(codec._chunks(&payload, false), vec![payload]);
+    }
+
+    #[test]
+    fn rsv1_is_set_only_on_the_first_fragment() {
+        let mut codec = WebsocketCodec::server()
+            .fragment_size(4)
+            .permessage_deflate(PerMessageDeflateConfig::default());
+
+        let frames = codec.encode_message(Message::from_text("0123456789".to_string())).unwrap();
+        assert!(frames.len() > 1, "payload should have been split into multiple frames");
+
+        for (i, frame) in frames.iter().enumerate() {
+            let first_byte = match frame {
+                FrameData::Complete(bytes) => bytes[0],
+                FrameData::Split(header, _) => header[0],
+            };
+
+            assert_eq!(first_byte & 0x40 != 0, i == 0, "RSV1 must only be set on the first fragment");
+        }
+    }
+
+    #[test]
+    fn chunks_never_fragments_control_frames() {
+        let codec = WebsocketCodec::server().fragment_size(2);
+        let payload = Bytes::from_static(b"0123456789");
+
+        assert_eq!(codec._chunks(&payload, true), vec![payload]);
+    }
 
-        buf.put(msg.buf);
+    #[test]
+    fn fragment_size_zero_is_treated_as_no_fragmentation() {
+        let codec = WebsocketCodec::server().fragment_size(0);
+        let payload = Bytes::from_static(b"0123456789");
 
-        Ok(())
+        assert_eq!(codec._chunks(&payload, false), vec![payload]);
     }
 }
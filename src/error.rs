@@ -14,6 +14,9 @@ pub enum WebsocketError {
     IoError(io::Error),
     SendError(SendError<Message>),
     FromUtf8Error(FromUtf8Error),
+    CompressError(flate2::CompressError),
+    DecompressError(flate2::DecompressError),
+    MessageTooBig,
 }
 
 impl fmt::Display for WebsocketError {
@@ -22,6 +25,9 @@ impl fmt::Display for WebsocketError {
             WebsocketError::IoError(ref err) => write!(f, "{}", err),
             WebsocketError::SendError(ref err) => write!(f, "{}", err),
             WebsocketError::FromUtf8Error(ref err) => write!(f, "{}", err),
+            WebsocketError::CompressError(ref err) => write!(f, "{}", err),
+            WebsocketError::DecompressError(ref err) => write!(f, "{}", err),
+            WebsocketError::MessageTooBig => write!(f, "decompressed message exceeds the configured max_message_size"),
         }
     }
 }
@@ -47,3 +53,15 @@ impl From<FromUtf8Error> for WebsocketError {
         WebsocketError::FromUtf8Error(err)
     }
 }
+
+impl From<flate2::CompressError> for WebsocketError {
+    fn from(err: flate2::CompressError) -> Self {
+        WebsocketError::CompressError(err)
+    }
+}
+
+impl From<flate2::DecompressError> for WebsocketError {
+    fn from(err: flate2::DecompressError) -> Self {
+        WebsocketError::DecompressError(err)
+    }
+}
@@ -50,10 +50,25 @@ use bytes::{ BytesMut, BufMut, Buf };
 use std::convert::TryInto;
 
 use crate::{ Frame, Opcode };
+use crate::frame::{ Role, CloseCode };
 
-#[derive(Debug, Default)]
+/// Default cap on a single frame's payload, matching actix's websocket codec default.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024;
+/// Default cap on the total payload of a (possibly fragmented) message.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+#[derive(Debug)]
 pub struct Builder {
     pub frame_index: usize,     // index of the frame in a fragmented message.
+    pub message_len: usize,     // bytes accumulated so far for the current fragmented message
+    pub role: Role,              // which side of the connection we're decoding for
+    pub max_frame_size: usize,  // per-frame payload cap
+    pub max_message_size: usize, // cap on the total payload across a fragmented message
+    pub compression_enabled: bool, // whether permessage-deflate (RSV1) was negotiated
+
+    text_message: bool,        // whether the in-progress (possibly fragmented) message is Text
+    compressed_message: bool,  // whether RSV1 was set on the first frame of this message
+    utf8_tail: Vec<u8>,        // incomplete trailing UTF-8 sequence carried over to the next frame
 
     first_byte_readed: bool,
     fin: bool,                  // 1 bit
@@ -72,6 +87,34 @@ pub struct Builder {
     buf: BytesMut,              // received data
 }
 
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            frame_index: 0,
+            message_len: 0,
+            role: Role::default(),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            compression_enabled: false,
+            text_message: false,
+            compressed_message: false,
+            utf8_tail: Vec::new(),
+            first_byte_readed: false,
+            fin: false,
+            rsv: 0,
+            opcode: Opcode::default(),
+            second_byte_readed: false,
+            mask: false,
+            payload_len: 0,
+            masking_key_readed: false,
+            masking_key: [0, 0, 0, 0],
+            buf_len_readed: false,
+            buf_len: 0,
+            buf: BytesMut::default(),
+        }
+    }
+}
+
 impl Builder {
     pub fn soft_reset(&mut self) {
         self.first_byte_readed = false;
@@ -93,17 +136,30 @@ impl Builder {
             self._read_fin(byte);
             self._read_rsv(byte);
 
-            if self.rsv != 0 {
-                return Some(Frame::create_close_with_code(1002))
-            }
+            // RSV1 signals a permessage-deflate compressed message and is only valid on
+            // the first frame of a non-control message when the extension was negotiated
+            // (https://tools.ietf.org/html/rfc7692#section-6). RSV2/RSV3 are unused.
+            let rsv1 = self.rsv & 0b100 != 0;
+            let other_rsv_bits = self.rsv & 0b011 != 0;
 
             self._read_opcode(byte);
 
+            if other_rsv_bits
+                || (rsv1 && !(self.compression_enabled && self.frame_index == 0 && !self.opcode.is_control()))
+            {
+                return Some(Frame::create_close_with_code(CloseCode::ProtocolError.into()))
+            }
+
             if (matches!(self.opcode, Opcode::RsvControl | Opcode::RsvNonControl))
                 || (self.frame_index == 0 && self.opcode == Opcode::Continuation)
                 || (self.frame_index > 0 && matches!(self.opcode, Opcode::Text | Opcode::Binary))
             {
-                return Some(Frame::create_close_with_code(1002))
+                return Some(Frame::create_close_with_code(CloseCode::ProtocolError.into()))
+            }
+
+            if self.frame_index == 0 && !self.opcode.is_control() {
+                self.text_message = self.opcode == Opcode::Text;
+                self.compressed_message = rsv1;
             }
 
             self.first_byte_readed = true;
@@ -117,13 +173,23 @@ impl Builder {
             self.second_byte_readed = true;
 
             if self.opcode.is_control() && (self.payload_len > 125 || self.fin == false) {
-                return Some(Frame::create_close_with_code(1002))
+                return Some(Frame::create_close_with_code(CloseCode::ProtocolError.into()))
+            }
+
+            if let Some(frame) = self._check_mask_violation() {
+                return Some(frame)
             }
         }
 
         if !self.buf_len_readed {
             self._read_buf_len(buf)?;
             self.buf_len_readed = true;
+
+            if self.buf_len > self.max_frame_size
+                || self.message_len + self.buf_len > self.max_message_size
+            {
+                return Some(Frame::create_close_with_code(CloseCode::TooBig.into()))
+            }
         }
 
         if self.mask && !self.masking_key_readed {
@@ -135,6 +201,18 @@ impl Builder {
             //panic!("Invalid buffer");
         }
 
+        if self.text_message && !self.compressed_message && !self.opcode.is_control() {
+            if let Some(close_frame) = self._validate_utf8() {
+                return Some(close_frame)
+            }
+        }
+
+        if self.fin && !self.opcode.is_control() {
+            self.text_message = false;
+            self.compressed_message = false;
+            self.utf8_tail.clear();
+        }
+
         let frame = Frame {
             fin: self.fin,
             rsv: self.rsv,
@@ -149,14 +227,53 @@ impl Builder {
         Some(frame)
     }
 
+    // A server only ever receives masked frames; a client only ever receives unmasked
+    // ones (https://tools.ietf.org/html/rfc6455#section-5.1). Anything else is a
+    // protocol violation, reported here as a 1002 (Protocol Error) close frame.
     #[inline(always)]
-    fn _read_buf(&mut self, buf: &mut BytesMut) -> Option<bool> {
-        if !self.mask { panic!("masking key is not set!") }
+    fn _check_mask_violation(&self) -> Option<Frame> {
+        let violation = match self.role {
+            Role::Server => !self.mask,
+            Role::Client => self.mask,
+        };
 
-        let idx = self.buf.len();
+        if violation {
+            return Some(Frame::create_close_with_code(CloseCode::ProtocolError.into()))
+        }
+
+        None
+    }
+
+    // Validates this frame's payload as UTF-8, carrying an incomplete trailing code point
+    // over to the next frame of the same message (`utf8_tail`) so that one split across
+    // fragment boundaries isn't mistaken for invalid data. Only genuinely malformed bytes,
+    // or an incomplete sequence left dangling at the end of the message, are rejected.
+    #[inline(always)]
+    fn _validate_utf8(&mut self) -> Option<Frame> {
+        let mut data = std::mem::take(&mut self.utf8_tail);
+        data.extend_from_slice(&self.buf);
+
+        match std::str::from_utf8(&data) {
+            Ok(_) => None,
+            Err(err) => match err.error_len() {
+                Some(_) => Some(Frame::create_close_with_code(CloseCode::InvalidPayload.into())),
+                None if self.fin => Some(Frame::create_close_with_code(CloseCode::InvalidPayload.into())),
+                None => {
+                    self.utf8_tail = data[err.valid_up_to()..].to_vec();
+                    None
+                },
+            },
+        }
+    }
+
+    #[inline(always)]
+    fn _read_buf(&mut self, buf: &mut BytesMut) -> Option<bool> {
+        if self.mask {
+            let idx = self.buf.len();
 
-        for i in idx..(idx+buf.len()) {
-            buf[i-idx] ^= self.masking_key[i % 4];
+            for i in idx..(idx+buf.len()) {
+                buf[i-idx] ^= self.masking_key[i % 4];
+            }
         }
 
         self.buf.put(buf);
@@ -230,3 +347,62 @@ impl Builder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a masked (client-to-server) frame with a 7-bit length header and a zero
+    // masking key, so `payload` is written to the wire unchanged.
+    fn masked_frame(fin: bool, opcode_bits: u8, payload: &[u8]) -> BytesMut {
+        let mut buf = BytesMut::new();
+        buf.put_u8((if fin { 0x80 } else { 0x00 }) | opcode_bits);
+        buf.put_u8(0x80 | payload.len() as u8);
+        buf.put_slice(&[0, 0, 0, 0]);
+        buf.put_slice(payload);
+        buf
+    }
+
+    #[test]
+    fn validates_utf8_split_across_fragment_boundary() {
+        let mut builder = Builder::default();
+
+        // "é" (0xC3 0xA9) split so the first frame ends mid-codepoint.
+        let mut first = masked_frame(false, 0x1, &[0xC3]);
+        let frame = builder.build(&mut first).unwrap();
+        assert_eq!(frame.opcode, Opcode::Text);
+        builder.soft_reset();
+        builder.frame_index = 1;
+        builder.message_len = frame.buf_len;
+
+        let mut second = masked_frame(true, 0x0, &[0xA9]);
+        let frame = builder.build(&mut second).unwrap();
+        assert_eq!(frame.opcode, Opcode::Continuation);
+        assert_eq!(&frame.buf[..], &[0xA9]);
+    }
+
+    #[test]
+    fn rejects_rsv1_on_a_control_frame_even_with_compression_enabled() {
+        let mut builder = Builder::default();
+        builder.compression_enabled = true;
+
+        // FIN | RSV1 | opcode=Ping (0x9): RSV1 is never legal on a control frame,
+        // compression negotiated or not (https://tools.ietf.org/html/rfc7692#section-6).
+        let mut buf = BytesMut::new();
+        buf.put_u8(0x80 | 0x40 | 0x9);
+        buf.put_u8(0x80); // masked, zero-length payload
+        buf.put_slice(&[0, 0, 0, 0]);
+
+        let frame = builder.build(&mut buf).unwrap();
+        assert_eq!(frame.opcode, Opcode::Close);
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        let mut builder = Builder::default();
+        let mut buf = masked_frame(true, 0x1, &[0xFF, 0xFE]);
+
+        let frame = builder.build(&mut buf).unwrap();
+        assert_eq!(frame.opcode, Opcode::Close);
+    }
+}
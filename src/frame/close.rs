@@ -0,0 +1,168 @@
+//! Structured representation of a Close frame's payload.
+//!
+//! [RFC 6455 7.4](https://tools.ietf.org/html/rfc6455#section-7.4) defines the status
+//! codes a Close frame may carry; this module mirrors that table plus the parsing rules
+//! for the optional UTF-8 reason that follows the code.
+
+/// A WebSocket close status code, as defined by
+/// [RFC 6455 7.4.1](https://tools.ietf.org/html/rfc6455#section-7.4.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseCode {
+    Normal,           // 1000
+    GoingAway,        // 1001
+    ProtocolError,    // 1002
+    UnsupportedData,  // 1003
+    InvalidPayload,   // 1007
+    PolicyViolation,  // 1008
+    TooBig,           // 1009
+    MandatoryExt,     // 1010
+    InternalError,    // 1011
+
+    // any code this crate doesn't recognize by name, including valid-but-unlisted ones
+    Other(u16),
+}
+
+impl From<u16> for CloseCode {
+    fn from(code: u16) -> Self {
+        match code {
+            1000 => CloseCode::Normal,
+            1001 => CloseCode::GoingAway,
+            1002 => CloseCode::ProtocolError,
+            1003 => CloseCode::UnsupportedData,
+            1007 => CloseCode::InvalidPayload,
+            1008 => CloseCode::PolicyViolation,
+            1009 => CloseCode::TooBig,
+            1010 => CloseCode::MandatoryExt,
+            1011 => CloseCode::InternalError,
+            code => CloseCode::Other(code),
+        }
+    }
+}
+
+impl From<CloseCode> for u16 {
+    fn from(code: CloseCode) -> Self {
+        match code {
+            CloseCode::Normal => 1000,
+            CloseCode::GoingAway => 1001,
+            CloseCode::ProtocolError => 1002,
+            CloseCode::UnsupportedData => 1003,
+            CloseCode::InvalidPayload => 1007,
+            CloseCode::PolicyViolation => 1008,
+            CloseCode::TooBig => 1009,
+            CloseCode::MandatoryExt => 1010,
+            CloseCode::InternalError => 1011,
+            CloseCode::Other(code) => code,
+        }
+    }
+}
+
+impl CloseCode {
+    /// Whether `code` is allowed to appear on the wire in a Close frame, per
+    /// [RFC 6455 7.4.1](https://tools.ietf.org/html/rfc6455#section-7.4.1): values below
+    /// 1000 are reserved, and 1005/1006/1015 are reserved for local use by endpoints to
+    /// report conditions where no actual close frame was received.
+    fn is_valid_on_wire(code: u16) -> bool {
+        !(code < 1000 || matches!(code, 1005 | 1006 | 1015))
+    }
+}
+
+/// The parsed payload of a Close frame: a status code followed by an optional UTF-8
+/// human-readable reason.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseReason {
+    pub code: CloseCode,
+    pub reason: String,
+}
+
+// A Close frame is a control frame, so RFC 6455 5.5 caps its whole payload at 125
+// bytes; 2 of those are the status code, leaving this many for the reason string.
+const MAX_REASON_LEN: usize = 125 - 2;
+
+impl CloseReason {
+    /// Builds a close reason, truncating `reason` (at a `char` boundary) so that
+    /// `code` plus the reason still fits within a control frame's 125-byte payload
+    /// limit ([RFC 6455 5.5](https://tools.ietf.org/html/rfc6455#section-5.5)).
+    pub fn new(code: CloseCode, reason: impl Into<String>) -> Self {
+        let mut reason = reason.into();
+
+        if reason.len() > MAX_REASON_LEN {
+            let mut truncate_at = MAX_REASON_LEN;
+            while !reason.is_char_boundary(truncate_at) {
+                truncate_at -= 1;
+            }
+            reason.truncate(truncate_at);
+        }
+
+        Self { code, reason }
+    }
+
+    /// Parse a Close frame payload: the first two bytes are a big-endian status code,
+    /// the remainder is a UTF-8 reason string. Returns `None` if the payload is empty
+    /// (no code was sent), the code is not allowed on the wire, or the reason isn't
+    /// valid UTF-8.
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.is_empty() { return None }
+        if buf.len() < 2 { return None }
+
+        let code = u16::from_be_bytes([buf[0], buf[1]]);
+
+        if !CloseCode::is_valid_on_wire(code) { return None }
+
+        let reason = String::from_utf8(buf[2..].to_vec()).ok()?;
+
+        Some(Self { code: CloseCode::from(code), reason })
+    }
+}
+
+impl From<CloseCode> for CloseReason {
+    fn from(code: CloseCode) -> Self {
+        Self { code, reason: String::new() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_keeps_short_reasons_untouched() {
+        let reason = CloseReason::new(CloseCode::Normal, "bye");
+        assert_eq!(reason.reason, "bye");
+    }
+
+    #[test]
+    fn new_truncates_reasons_that_would_overflow_a_control_frame() {
+        let long = "a".repeat(200);
+        let reason = CloseReason::new(CloseCode::Normal, long);
+
+        assert_eq!(reason.reason.len(), MAX_REASON_LEN);
+    }
+
+    #[test]
+    fn new_truncates_on_a_char_boundary() {
+        // Each "é" is 2 bytes; 62 of them is 124 bytes, one over `MAX_REASON_LEN`,
+        // so a naive byte-index truncation would split the last character in half.
+        let long = "é".repeat(62);
+        let reason = CloseReason::new(CloseCode::Normal, long);
+
+        assert!(reason.reason.len() <= MAX_REASON_LEN);
+        assert!(std::str::from_utf8(reason.reason.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn parse_reads_code_and_reason() {
+        let mut buf = vec![0x03, 0xE8]; // 1000
+        buf.extend_from_slice(b"done");
+
+        let reason = CloseReason::parse(&buf).unwrap();
+
+        assert_eq!(reason.code, CloseCode::Normal);
+        assert_eq!(reason.reason, "done");
+    }
+
+    #[test]
+    fn parse_rejects_reserved_codes() {
+        let buf = [0x03, 0xED]; // 1005, reserved for local use only
+        assert!(CloseReason::parse(&buf).is_none());
+    }
+}
@@ -1,11 +1,42 @@
-use bytes::{ BytesMut, BufMut };
+use bytes::{ Bytes, BytesMut, BufMut };
 
 pub mod builder;
+pub mod close;
 pub mod opcode;
 
 pub use builder::Builder as FrameBuilder;
+pub use close::{ CloseCode, CloseReason };
 pub use opcode::Opcode;
 
+/// An outgoing frame, ready to be written to the wire.
+///
+/// Most frames are cheap to assemble into one contiguous buffer (`Complete`), but a
+/// large payload shouldn't be copied a second time just to join it with its few-byte
+/// header: `Split` keeps the header and the original payload `Bytes` apart so the write
+/// loop can write the payload by reference.
+#[derive(Debug)]
+pub(crate) enum FrameData {
+    Complete(Bytes),
+    Split(Bytes, Bytes),
+}
+
+/// Which side of the connection a codec/builder is acting as.
+///
+/// Per [RFC 6455 5.1](https://tools.ietf.org/html/rfc6455#section-5.1), frames sent by a
+/// client MUST be masked and frames sent by a server MUST NOT be masked; the receiving
+/// side enforces the opposite rule. `Role` threads that distinction through
+/// `WebsocketCodec`/`FrameBuilder` so the same code can run on either side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+impl Default for Role {
+    /// Historically this crate only ever spoke to browsers, i.e. acted as a server.
+    fn default() -> Self { Role::Server }
+}
+
 #[derive(Debug, Default)]
 pub struct Frame {
     pub fin: bool,              // 1 bit
@@ -31,9 +62,10 @@ impl Frame {
         !self.is_control()
     }
 
-    pub fn create_close_with_code(code: u16) -> Self {
+    pub fn create_close_with_code(reason: CloseReason) -> Self {
         let mut buf = BytesMut::new();
-        buf.put_u16(code);
+        buf.put_u16(reason.code.into());
+        buf.put_slice(reason.reason.as_bytes());
 
         Self {
             fin: true,
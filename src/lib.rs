@@ -3,28 +3,44 @@
 use std::marker::Unpin;
 
 use base64::encode;
-use futures::sink::SinkExt;
 use sha1::Sha1;
 use tokio::io::{ AsyncRead, AsyncWrite, AsyncWriteExt };
 use tokio::io::{ split, ReadHalf, WriteHalf };
 use tokio::stream::StreamExt;
 use tokio::sync::mpsc;
-use tokio_util::codec::{ FramedRead, FramedWrite };
+use tokio_util::codec::FramedRead;
 
 pub mod error;
 pub mod codec;
 pub mod message;
 pub mod frame;
+pub mod permessage_deflate;
 
 pub use error::{ WebsocketError, WebsocketResult };
 pub use codec::WebsocketCodec;
 pub use message::Message;
+pub use frame::{ CloseCode, CloseReason };
+pub use permessage_deflate::PerMessageDeflateConfig;
 use frame::{
     Frame,
     FrameBuilder,
+    FrameData,
     Opcode,
+    Role,
 };
 
+/// Tunable limits and extensions for a `Websocket`, on top of the bare `new`/`client`
+/// defaults ([`frame::builder::DEFAULT_MAX_FRAME_SIZE`]/
+/// [`frame::builder::DEFAULT_MAX_MESSAGE_SIZE`], no auto-fragmentation, no
+/// `permessage-deflate`). `None` in any field keeps that default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WebsocketConfig {
+    pub max_frame_size: Option<usize>,
+    pub max_message_size: Option<usize>,
+    pub fragment_size: Option<usize>,
+    pub deflate: Option<PerMessageDeflateConfig>,
+}
+
 #[derive(Debug)]
 pub struct Websocket<S> {
     pub tx: mpsc::Sender<Message>,
@@ -39,30 +55,67 @@ where
 {
     /// Create a new websocket instance, given any type that implements
     /// `AsyncRead + AsyncWrite` like `tokio::net::TcpStream` or `tokio_native_tls::TlsStream`
+    ///
+    /// Acts as the server side of the connection: incoming frames must be masked and
+    /// outgoing frames are sent unmasked. Use [`Websocket::client`] for the other side.
     pub fn new(stream: S) -> Self {
-        Self::_create(stream, None)
+        Self::_create(stream, None, Role::Server, WebsocketConfig::default())
     }
 
     /// Same as `Websocket::new` except that it also accept a key that represent
     /// the value of `Sec-Websocket-Key` for client that requires a valid
     /// `Sec-Websocket-Accept` in response headers.
     pub fn new_with_key(stream: S, key: String) -> Self {
-        Self::_create(stream, Some(key))
+        Self::_create(stream, Some(key), Role::Server, WebsocketConfig::default())
+    }
+
+    /// Create a new websocket instance acting as the *client* side of the connection:
+    /// outgoing frames are masked with a fresh random key per frame and incoming
+    /// (server) frames must be unmasked. No handshake response is sent, since a client
+    /// is the one receiving the handshake rather than producing it.
+    pub fn client(stream: S) -> Self {
+        Self::_create(stream, None, Role::Client, WebsocketConfig::default())
     }
 
-    fn _create(stream: S, key: Option<String>) -> Self {
+    /// Same as [`Websocket::new_with_key`], but also negotiates the `permessage-deflate`
+    /// extension (RFC 7692) with the given parameters.
+    pub fn new_with_deflate(stream: S, key: String, deflate: PerMessageDeflateConfig) -> Self {
+        let config = WebsocketConfig { deflate: Some(deflate), ..WebsocketConfig::default() };
+        Self::_create(stream, Some(key), Role::Server, config)
+    }
+
+    /// Same as `Websocket::new`/`Websocket::client`, but lets the frame/message size
+    /// caps, auto-fragmentation and `permessage-deflate` be configured instead of left
+    /// at their defaults.
+    pub fn with_config(stream: S, key: Option<String>, role: Role, config: WebsocketConfig) -> Self {
+        Self::_create(stream, key, role, config)
+    }
+
+    fn _create(stream: S, key: Option<String>, role: Role, config: WebsocketConfig) -> Self {
         let (reader, mut writer) = split(stream);
-        let reader = FramedRead::new(reader, WebsocketCodec::default());
+        let reader = FramedRead::new(reader, Self::_codec(role, config));
         let (tx, mut rx) = mpsc::channel::<Message>(100);
 
         tokio::spawn(async move {
-            Self::_send_handshake(&mut writer, key).await;
+            if role == Role::Server {
+                Self::_send_handshake(&mut writer, key, config.deflate).await;
+            }
 
-            let mut writer = FramedWrite::new(writer, WebsocketCodec::default());
+            let mut codec = Self::_codec(role, config);
 
             while let Some(msg) = rx.recv().await {
                 let is_close = msg.is_close;
-                writer.send(msg).await.unwrap();
+
+                for frame in codec.encode_message(msg).unwrap() {
+                    match frame {
+                        FrameData::Complete(bytes) => writer.write_all(&bytes).await.unwrap(),
+                        FrameData::Split(header, payload) => {
+                            writer.write_all(&header).await.unwrap();
+                            writer.write_all(&payload).await.unwrap();
+                        },
+                    }
+                }
+
                 if is_close { break }
             }
         });
@@ -70,8 +123,27 @@ where
         Self { reader, tx, key: None }
     }
 
+    fn _codec(role: Role, config: WebsocketConfig) -> WebsocketCodec {
+        let mut codec = if role == Role::Client { WebsocketCodec::client() } else { WebsocketCodec::server() };
+
+        if let Some(size) = config.max_frame_size {
+            codec = codec.max_frame_size(size);
+        }
+        if let Some(size) = config.max_message_size {
+            codec = codec.max_message_size(size);
+        }
+        if let Some(size) = config.fragment_size {
+            codec = codec.fragment_size(size);
+        }
+        if let Some(deflate) = config.deflate {
+            codec = codec.permessage_deflate(deflate);
+        }
+
+        codec
+    }
+
     /// Send handshake.
-    async fn _send_handshake(writer: &mut WriteHalf<S>, key: Option<String>) {
+    async fn _send_handshake(writer: &mut WriteHalf<S>, key: Option<String>, deflate: Option<PerMessageDeflateConfig>) {
         let mut handshake = vec![
             "HTTP/1.1 101 Switching Protocols".to_string(),
             "Upgrade: websocket".to_string(),
@@ -86,6 +158,19 @@ where
             handshake.push(key);
         }
 
+        if let Some(config) = deflate {
+            let mut extension = "Sec-WebSocket-Extensions: permessage-deflate".to_string();
+
+            if config.server_no_context_takeover {
+                extension.push_str("; server_no_context_takeover");
+            }
+            if config.client_no_context_takeover {
+                extension.push_str("; client_no_context_takeover");
+            }
+
+            handshake.push(extension);
+        }
+
         handshake.push("\r\n".to_string());
 
         writer.write_all(handshake.join("\r\n").as_bytes()).await.unwrap();
@@ -109,7 +194,20 @@ where
                 frames.push(frame);
 
                 if is_last {
-                    return Some(Message::from_non_control_frames(frames))
+                    let opcode = frames[0].opcode;
+
+                    match self.reader.decoder_mut().assemble_message_payload(opcode, &frames) {
+                        Ok(buf) => return Some(Message::from_non_control_frames(opcode, buf)),
+                        Err(err) => {
+                            let code = match err {
+                                WebsocketError::MessageTooBig => CloseCode::TooBig,
+                                _ => CloseCode::InvalidPayload,
+                            };
+                            let close_frame = Frame::create_close_with_code(code.into());
+                            self.tx.send(Message::from_close(close_frame)).await.unwrap();
+                            break
+                        },
+                    }
                 }
             }
         }
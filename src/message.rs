@@ -1,6 +1,7 @@
-use bytes::{ Bytes, BytesMut, BufMut };
+use bytes::Bytes;
 
-use crate::{ Frame, Opcode };
+use crate::{ Frame, Opcode, WebsocketResult };
+use crate::frame::CloseReason;
 
 #[derive(Debug, Default)]
 pub struct Message {
@@ -9,8 +10,6 @@ pub struct Message {
     pub is_close: bool,
     pub is_pong: bool,
     pub buf: Bytes,
-
-    frames: Vec<Frame>,
 }
 
 impl Message {
@@ -28,33 +27,32 @@ impl Message {
         }
     }
 
-    pub fn from_non_control_frames(frames: Vec<Frame>) -> Self {
-        assert!(frames.len() > 0);
-
+    /// Build a text/binary `Message` from a complete (possibly decompressed) message
+    /// payload, tagged with the opcode of the first frame of the message.
+    pub fn from_non_control_frames(opcode: Opcode, buf: Bytes) -> Self {
         Self {
-            is_text: frames[0].opcode.is_text(),
-            is_binary: frames[0].opcode.is_binary(),
-            frames,
+            is_text: opcode.is_text(),
+            is_binary: opcode.is_binary(),
+            buf,
             ..Default::default()
         }
     }
 
-    pub fn text(&self) -> String {
+    /// Decode this message's payload as UTF-8 text.
+    ///
+    /// A received `Message` has already had its payload validated as UTF-8 (incrementally,
+    /// frame by frame, while the message was being assembled, or once after inflating a
+    /// compressed message), so this should never fail in practice.
+    pub fn text(&self) -> WebsocketResult<String> {
         assert!(self.is_text);
 
-        String::from("hello")
+        Ok(String::from_utf8(self.buf.to_vec())?)
     }
 
     pub fn binary(&self) -> Bytes {
         assert!(self.is_binary);
 
-        let mut buf = BytesMut::new();
-
-        for frame in self.frames.iter() {
-            buf.put_slice(&frame.buf[..]);
-        }
-
-        buf.freeze()
+        self.buf.clone()
     }
 
     pub fn from_close(frame: Frame) -> Self {
@@ -65,6 +63,15 @@ impl Message {
         }
     }
 
+    /// Parse this Close message's payload into a structured code + reason, per
+    /// [RFC 6455 7.4](https://tools.ietf.org/html/rfc6455#section-7.4). Returns `None`
+    /// if no code was sent or the payload doesn't conform to the spec.
+    pub fn close_reason(&self) -> Option<CloseReason> {
+        assert!(self.is_close);
+
+        CloseReason::parse(&self.buf)
+    }
+
     pub fn from_text(text: String) -> Self {
         Self {
             is_text: true,
@@ -0,0 +1,168 @@
+//! `permessage-deflate` extension (RSV1), as defined by
+//! [RFC 7692](https://tools.ietf.org/html/rfc7692).
+//!
+//! The wire format is raw DEFLATE (no zlib header): a sender compresses the message,
+//! appends the 4-byte marker `00 00 FF FF` with `Flush::Sync`, then strips that trailing
+//! marker back off before sending; a receiver appends the marker back on before
+//! inflating. `*_no_context_takeover` controls whether the sliding-window dictionary is
+//! reset between messages, independently for each direction.
+
+use bytes::{ Bytes, BytesMut };
+use flate2::{ Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status };
+
+use crate::frame::Role;
+use crate::{ WebsocketError, WebsocketResult };
+
+// RFC 7692 7.2.1: the last 4 bytes of a deflated message are always this empty block,
+// and must be stripped/re-appended across the wire.
+const EMPTY_DEFLATE_BLOCK: [u8; 4] = [0x00, 0x00, 0xFF, 0xFF];
+
+/// Negotiated `permessage-deflate` parameters
+/// (see [RFC 7692 7.1](https://tools.ietf.org/html/rfc7692#section-7.1)).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PerMessageDeflateConfig {
+    pub client_no_context_takeover: bool,
+    pub server_no_context_takeover: bool,
+}
+
+/// Per-connection permessage-deflate compressor/decompressor state.
+#[derive(Debug)]
+pub struct PerMessageDeflate {
+    compress: Compress,
+    decompress: Decompress,
+    reset_compress_after_message: bool,
+    reset_decompress_after_message: bool,
+}
+
+impl PerMessageDeflate {
+    /// `role` picks out which side of `config` governs which direction: our own
+    /// outgoing context takeover is `role`'s setting, the peer's incoming one is the
+    /// other side's.
+    pub fn new(config: PerMessageDeflateConfig, role: Role) -> Self {
+        let (reset_compress_after_message, reset_decompress_after_message) = match role {
+            Role::Client => (config.client_no_context_takeover, config.server_no_context_takeover),
+            Role::Server => (config.server_no_context_takeover, config.client_no_context_takeover),
+        };
+
+        Self {
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+            reset_compress_after_message,
+            reset_decompress_after_message,
+        }
+    }
+
+    /// Inflate a whole message's concatenated, already-unmasked payload.
+    ///
+    /// `max_message_size` bounds the *decompressed* output, independently of whatever
+    /// cap was already enforced on the compressed wire bytes: a small compressed
+    /// payload can inflate to an arbitrarily large one, so the limit has to be checked
+    /// as output is produced rather than on the input.
+    pub fn decompress(&mut self, payload: &[u8], max_message_size: usize) -> WebsocketResult<Bytes> {
+        let mut input = BytesMut::with_capacity(payload.len() + EMPTY_DEFLATE_BLOCK.len());
+        input.extend_from_slice(payload);
+        input.extend_from_slice(&EMPTY_DEFLATE_BLOCK);
+
+        let mut out = BytesMut::with_capacity(payload.len() * 2);
+        let mut chunk = [0u8; 4096];
+        let start_in = self.decompress.total_in();
+
+        loop {
+            let consumed = (self.decompress.total_in() - start_in) as usize;
+            let before_out = self.decompress.total_out();
+
+            let status = self.decompress.decompress(
+                &input[consumed..],
+                &mut chunk,
+                FlushDecompress::Sync,
+            ).map_err(WebsocketError::from)?;
+
+            out.extend_from_slice(&chunk[..(self.decompress.total_out() - before_out) as usize]);
+
+            if out.len() > max_message_size {
+                // Reset so a later message on the same connection doesn't inherit a
+                // half-consumed stream from this rejected one.
+                self.decompress.reset(false);
+                return Err(WebsocketError::MessageTooBig)
+            }
+
+            let produced_nothing = self.decompress.total_out() == before_out;
+            let consumed_everything = (self.decompress.total_in() - start_in) as usize >= input.len();
+
+            if status == Status::StreamEnd || consumed_everything || produced_nothing {
+                break
+            }
+        }
+
+        if self.reset_decompress_after_message {
+            self.decompress.reset(false);
+        }
+
+        Ok(out.freeze())
+    }
+
+    /// Deflate a whole message's payload, ready to be split across outgoing frames.
+    pub fn compress(&mut self, payload: &[u8]) -> WebsocketResult<Bytes> {
+        let mut out = BytesMut::with_capacity(payload.len());
+        let mut chunk = [0u8; 4096];
+        let start_in = self.compress.total_in();
+
+        loop {
+            let consumed = (self.compress.total_in() - start_in) as usize;
+            let before_out = self.compress.total_out();
+
+            let status = self.compress.compress(
+                &payload[consumed..],
+                &mut chunk,
+                FlushCompress::Sync,
+            ).map_err(WebsocketError::from)?;
+
+            out.extend_from_slice(&chunk[..(self.compress.total_out() - before_out) as usize]);
+
+            if status == Status::StreamEnd || (self.compress.total_in() - start_in) as usize >= payload.len() {
+                break
+            }
+        }
+
+        if out.ends_with(&EMPTY_DEFLATE_BLOCK) {
+            let trimmed = out.len() - EMPTY_DEFLATE_BLOCK.len();
+            out.truncate(trimmed);
+        }
+
+        if self.reset_compress_after_message {
+            self.compress.reset();
+        }
+
+        Ok(out.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_a_message() {
+        let mut deflate = PerMessageDeflate::new(PerMessageDeflateConfig::default(), Role::Client);
+        let payload = b"hello hello hello hello websocket websocket";
+
+        let compressed = deflate.compress(payload).unwrap();
+        let decompressed = deflate.decompress(&compressed, payload.len() + 1).unwrap();
+
+        assert_eq!(&decompressed[..], &payload[..]);
+    }
+
+    #[test]
+    fn rejects_output_over_max_message_size() {
+        let mut compressor = PerMessageDeflate::new(PerMessageDeflateConfig::default(), Role::Client);
+        let mut decompressor = PerMessageDeflate::new(PerMessageDeflateConfig::default(), Role::Server);
+
+        // Highly compressible, so the compressed frame stays tiny while the inflated
+        // output would blow straight past a small cap.
+        let payload = vec![0u8; 1_000_000];
+        let compressed = compressor.compress(&payload).unwrap();
+
+        let err = decompressor.decompress(&compressed, 1024).unwrap_err();
+        assert!(matches!(err, WebsocketError::MessageTooBig));
+    }
+}